@@ -1,6 +1,7 @@
-use half::f16;
+use half::{bf16, f16};
 use rayon::prelude::*;
-use rustler::{Atom, Binary, Env, OwnedBinary, Term, Encoder};
+use rustler::{Binary, Env, OwnedBinary, Term, Encoder};
+use std::borrow::Cow;
 
 rustler::init!("Elixir.Thunderline.Thunderbolt.Numerics.Native");
 
@@ -23,20 +24,102 @@ fn error<'a>(env: Env<'a>, reason: &str) -> Term<'a> {
     }
 }
 
+/// Common surface of the half-precision float types (`f16`, `bf16`) that the
+/// GEMM kernels need: bit-level (de)serialization, the bulk slice
+/// conversions `half` provides for each type, and the largest finite value.
+trait HalfFloat: Copy + bytemuck::Pod {
+    fn from_bits(bits: u16) -> Self;
+    fn to_bits(self) -> u16;
+    fn slice_to_f32(src: &[Self]) -> Vec<f32>;
+    fn slice_from_f32(dst: &mut [Self], src: &[f32]);
+    /// Largest finite magnitude this type can represent, as an f32. Values
+    /// clamped to this before narrowing never overflow to infinity.
+    fn max_finite_f32() -> f32;
+}
+
+impl HalfFloat for f16 {
+    #[inline]
+    fn from_bits(bits: u16) -> Self {
+        f16::from_bits(bits)
+    }
+    #[inline]
+    fn to_bits(self) -> u16 {
+        f16::to_bits(self)
+    }
+    #[inline]
+    fn slice_to_f32(src: &[f16]) -> Vec<f32> {
+        use half::slice::HalfFloatSliceExt;
+        src.to_f32_vec()
+    }
+    #[inline]
+    fn slice_from_f32(dst: &mut [f16], src: &[f32]) {
+        use half::slice::HalfFloatSliceExt;
+        dst.convert_from_f32_slice(src)
+    }
+    #[inline]
+    fn max_finite_f32() -> f32 {
+        f32::from(f16::MAX)
+    }
+}
+
+impl HalfFloat for bf16 {
+    #[inline]
+    fn from_bits(bits: u16) -> Self {
+        bf16::from_bits(bits)
+    }
+    #[inline]
+    fn to_bits(self) -> u16 {
+        bf16::to_bits(self)
+    }
+    #[inline]
+    fn slice_to_f32(src: &[bf16]) -> Vec<f32> {
+        use half::slice::HalfFloatSliceExt;
+        src.to_f32_vec()
+    }
+    #[inline]
+    fn slice_from_f32(dst: &mut [bf16], src: &[f32]) {
+        // Rounds to nearest (ties-to-even), not a truncating cast; pinned in
+        // Cargo.toml to a `half` version where this already holds.
+        use half::slice::HalfFloatSliceExt;
+        dst.convert_from_f32_slice(src)
+    }
+    #[inline]
+    fn max_finite_f32() -> f32 {
+        f32::from(bf16::MAX)
+    }
+}
+
+/// Views a little-endian half-precision byte buffer as `&[T]` without
+/// copying. `f16`/`bf16` share `u16`'s bit layout, and on little-endian
+/// targets (the only ones the BEAM + this NIF ship on) that layout matches
+/// the wire format directly, so `bytemuck` can reinterpret the buffer in
+/// place instead of us decoding it element by element. Falls back to the
+/// owned decode if the binary happens to be misaligned for `u16` access.
 #[inline]
-fn read_fp16_le_slice(bytes: &[u8]) -> Vec<f16> {
-    // Interpret little-endian u16 pairs as f16
-    bytes
-        .chunks_exact(2)
-        .map(|c| {
-            let u = u16::from_le_bytes([c[0], c[1]]);
-            f16::from_bits(u)
-        })
-        .collect()
+fn reinterpret_half_le<T: HalfFloat>(bytes: &[u8]) -> Cow<'_, [T]> {
+    #[cfg(target_endian = "little")]
+    if let Ok(half_slice) = bytemuck::try_cast_slice::<u8, T>(bytes) {
+        return Cow::Borrowed(half_slice);
+    }
+
+    Cow::Owned(
+        bytes
+            .chunks_exact(2)
+            .map(|c| T::from_bits(u16::from_le_bytes([c[0], c[1]])))
+            .collect(),
+    )
 }
 
+/// Writes `data` out as little-endian bytes, reinterpreting `buf` in place
+/// when alignment allows instead of packing byte-by-byte.
 #[inline]
-fn write_fp16_le_slice(buf: &mut [u8], data: &[f16]) {
+fn write_half_le_slice<T: HalfFloat>(buf: &mut [u8], data: &[T]) {
+    #[cfg(target_endian = "little")]
+    if let Ok(out_slice) = bytemuck::try_cast_slice_mut::<u8, T>(buf) {
+        out_slice.copy_from_slice(data);
+        return;
+    }
+
     for (i, &h) in data.iter().enumerate() {
         let u = h.to_bits();
         let [b0, b1] = u.to_le_bytes();
@@ -46,10 +129,195 @@ fn write_fp16_le_slice(buf: &mut [u8], data: &[f16]) {
     }
 }
 
-/// gemm_fp16_acc32(a_bin, b_bin, m, n, k) -> c_bin
-/// A: (m×k) FP16 row-major, B: (k×n) FP16 row-major, C: (m×n) FP16 row-major
-#[rustler::nif(schedule = "DirtyCpu")]
-pub fn gemm_fp16_acc32<'a>(env: Env<'a>, a: Binary<'a>, b: Binary<'a>, m: usize, n: usize, k: usize) -> Term<'a> {
+/// Default cache-block sizes for the packed GEMM below, sized for a
+/// ~1MB L2 desktop-class core: an `MC×KC` A-panel and a `KC×NC` B-panel
+/// of f32 are 256KB each, so both fit in L2 together (neither fits a 32KB
+/// L1). Exposed as NIF parameters so callers can retune per target; `0`
+/// means "use this default".
+const DEFAULT_MC: usize = 256;
+const DEFAULT_NC: usize = 256;
+const DEFAULT_KC: usize = 256;
+
+/// Gathers `A[i0+i, p0+p]` (or its transpose) for `i in 0..mb, p in 0..kb`
+/// into a contiguous `mb×kb` row-major scratch buffer.
+#[inline]
+#[allow(clippy::too_many_arguments)]
+fn pack_a_block(a_f32: &[f32], m: usize, k: usize, trans_a: bool, i0: usize, p0: usize, mb: usize, kb: usize) -> Vec<f32> {
+    let mut packed = vec![0f32; mb * kb];
+    for i in 0..mb {
+        for p in 0..kb {
+            packed[i * kb + p] = if trans_a {
+                a_f32[(p0 + p) * m + (i0 + i)]
+            } else {
+                a_f32[(i0 + i) * k + (p0 + p)]
+            };
+        }
+    }
+    packed
+}
+
+/// Gathers `B[p0+p, j0+j]` (or its transpose) for `p in 0..kb, j in 0..nb`
+/// into a contiguous `kb×nb` row-major scratch buffer.
+#[inline]
+#[allow(clippy::too_many_arguments)]
+fn pack_b_panel(b_f32: &[f32], n: usize, k: usize, trans_b: bool, p0: usize, j0: usize, kb: usize, nb: usize) -> Vec<f32> {
+    let mut packed = vec![0f32; kb * nb];
+    for p in 0..kb {
+        for j in 0..nb {
+            packed[p * nb + j] = if trans_b {
+                b_f32[(j0 + j) * k + (p0 + p)]
+            } else {
+                b_f32[(p0 + p) * n + (j0 + j)]
+            };
+        }
+    }
+    packed
+}
+
+/// Computes `A(m×k) * B(k×n)` (or with `A`/`B` transposed) in f32 as a
+/// classic three-level blocked GEMM: iterate (NC, KC, MC) tiles, pack each
+/// A/B block into small contiguous scratch so the micro-kernel's hot data
+/// stays L1/L2-resident, and keep rayon parallelism at the MC-block level.
+/// The B panel is packed once per (jc, pc) and reused across every MC
+/// block; each MC block packs its own A block since different blocks run
+/// on different threads. `mc`/`nc`/`kc` must already be clamped to
+/// `1..=m`/`1..=n`/`1..=k` by the caller.
+#[inline]
+#[allow(clippy::too_many_arguments)]
+fn blocked_gemm_f32(
+    a_f32: &[f32],
+    b_f32: &[f32],
+    m: usize,
+    n: usize,
+    k: usize,
+    trans_a: bool,
+    trans_b: bool,
+    mc: usize,
+    nc: usize,
+    kc: usize,
+) -> Vec<f32> {
+    let mut c_f32 = vec![0f32; m * n];
+
+    let mut jc = 0;
+    while jc < n {
+        let nb = (n - jc).min(nc);
+        let mut pc = 0;
+        while pc < k {
+            let kb = (k - pc).min(kc);
+            let b_packed = pack_b_panel(b_f32, n, k, trans_b, pc, jc, kb, nb);
+
+            c_f32.par_chunks_mut(mc * n).enumerate().for_each(|(ic_idx, c_block)| {
+                let i0 = ic_idx * mc;
+                let mb = c_block.len() / n;
+                let a_packed = pack_a_block(a_f32, m, k, trans_a, i0, pc, mb, kb);
+
+                for i in 0..mb {
+                    let a_row = &a_packed[i * kb..(i + 1) * kb];
+                    for j in 0..nb {
+                        let mut acc: f32 = 0.0;
+                        // FMA micro-kernel over the packed, cache-resident blocks
+                        let mut p = 0;
+                        while p + 7 < kb {
+                            acc = a_row[p].mul_add(b_packed[p * nb + j], acc);
+                            acc = a_row[p + 1].mul_add(b_packed[(p + 1) * nb + j], acc);
+                            acc = a_row[p + 2].mul_add(b_packed[(p + 2) * nb + j], acc);
+                            acc = a_row[p + 3].mul_add(b_packed[(p + 3) * nb + j], acc);
+                            acc = a_row[p + 4].mul_add(b_packed[(p + 4) * nb + j], acc);
+                            acc = a_row[p + 5].mul_add(b_packed[(p + 5) * nb + j], acc);
+                            acc = a_row[p + 6].mul_add(b_packed[(p + 6) * nb + j], acc);
+                            acc = a_row[p + 7].mul_add(b_packed[(p + 7) * nb + j], acc);
+                            p += 8;
+                        }
+                        while p < kb {
+                            acc = a_row[p].mul_add(b_packed[p * nb + j], acc);
+                            p += 1;
+                        }
+                        c_block[i * n + jc + j] += acc;
+                    }
+                }
+            });
+
+            pc += kb;
+        }
+        jc += nb;
+    }
+
+    c_f32
+}
+
+/// Blends `c` (the freshly computed `A*B`) with an optional running total in
+/// place: `c = alpha*c + beta*c_in` when `c_in` is `Some`, or plain
+/// `c = alpha*c` otherwise. `c_in` must be the same length as `c` (the
+/// caller already validated this against `m*n` before decoding it).
+#[inline]
+fn apply_alpha_beta(c: &mut [f32], c_in: Option<&[f32]>, alpha: f32, beta: f32) {
+    match c_in {
+        Some(c_in) => {
+            for (v, c0) in c.iter_mut().zip(c_in.iter()) {
+                *v = alpha * *v + beta * c0;
+            }
+        }
+        None if alpha != 1.0 => {
+            for v in c.iter_mut() {
+                *v *= alpha;
+            }
+        }
+        None => {}
+    }
+}
+
+/// Clamps finite values in `c` to `T`'s representable range in place, so the
+/// downcast to `T` below can't turn a finite value into an infinity. NaN/inf
+/// already present in `c` pass through unchanged.
+#[inline]
+fn saturate_finite<T: HalfFloat>(c: &mut [f32]) {
+    let max_finite = T::max_finite_f32();
+    for v in c.iter_mut() {
+        if v.is_finite() {
+            *v = v.clamp(-max_finite, max_finite);
+        }
+    }
+}
+
+/// Returns whether narrowing `c_f32` down to `c_half` lost a finite value to
+/// infinity purely through the downcast to `T` (as opposed to `c_f32`
+/// already holding a NaN/inf, which narrows to the matching NaN/inf and
+/// isn't range loss).
+#[inline]
+fn check_strict_overflow<T: HalfFloat>(c_f32: &[f32], c_half: &[T]) -> bool {
+    let c_half_f32 = T::slice_to_f32(c_half);
+    c_f32
+        .iter()
+        .zip(c_half_f32.iter())
+        .any(|(orig, narrowed)| orig.is_finite() && !narrowed.is_finite())
+}
+
+/// Shared tiled A(m×k)·B(k×n) accumulation in f32, generic over the
+/// half-precision type used on the wire. Computes `C = alpha*(op_a(A)*op_b(B)) + beta*C_in`,
+/// matching the BLAS GEMM contract; `gemm_fp16_acc32`/`gemm_bf16_acc32` are the
+/// `alpha=1, beta=0, trans_a=false, trans_b=false` special case and never
+/// touch `c_in`. `gemm_fp16` exposes the full signature. `trans_a`/`trans_b`
+/// mean the `a`/`b` binary already holds the transpose (k×m / n×k row-major)
+/// rather than requiring the caller to materialize one.
+#[allow(clippy::too_many_arguments)]
+fn gemm_acc32<'a, T: HalfFloat + Send + Sync>(
+    env: Env<'a>,
+    a: Binary<'a>,
+    b: Binary<'a>,
+    c_in: Option<&[u8]>,
+    m: usize,
+    n: usize,
+    k: usize,
+    alpha: f32,
+    beta: f32,
+    trans_a: bool,
+    trans_b: bool,
+    mc: usize,
+    nc: usize,
+    kc: usize,
+    saturate: bool,
+    strict: bool,
+) -> Term<'a> {
     // Validate shapes vs. buffers
     let expected_a = m.checked_mul(k).and_then(|x| x.checked_mul(2)).unwrap_or(usize::MAX);
     let expected_b = k.checked_mul(n).and_then(|x| x.checked_mul(2)).unwrap_or(usize::MAX);
@@ -57,57 +325,344 @@ pub fn gemm_fp16_acc32<'a>(env: Env<'a>, a: Binary<'a>, b: Binary<'a>, m: usize,
         return error(env, "badarg");
     }
 
-    // Decode to f32 accum types
-    let a_half = read_fp16_le_slice(a.as_slice());
-    let b_half = read_fp16_le_slice(b.as_slice());
-    let a_f32: Vec<f32> = a_half.iter().map(|h| f32::from(*h)).collect();
-    let b_f32: Vec<f32> = b_half.iter().map(|h| f32::from(*h)).collect();
-
-    // Compute C = A(m×k) * B(k×n) in f32
     let mn = match m.checked_mul(n) {
         Some(v) => v,
         None => return error(env, "overflow"),
     };
-    let mut c_f32 = vec![0f32; mn];
 
-    // Parallelize by rows for cache-friendliness
-    c_f32
-        .par_chunks_mut(n)
-        .enumerate()
-        .for_each(|(i, row)| {
-            let a_row = &a_f32[i * k..(i + 1) * k];
-            for j in 0..n {
-                let mut acc: f32 = 0.0;
-                // micro-tile along k
-                let mut p = 0;
-                while p + 7 < k {
-                    acc += a_row[p + 0] * b_f32[(p + 0) * n + j];
-                    acc += a_row[p + 1] * b_f32[(p + 1) * n + j];
-                    acc += a_row[p + 2] * b_f32[(p + 2) * n + j];
-                    acc += a_row[p + 3] * b_f32[(p + 3) * n + j];
-                    acc += a_row[p + 4] * b_f32[(p + 4) * n + j];
-                    acc += a_row[p + 5] * b_f32[(p + 5) * n + j];
-                    acc += a_row[p + 6] * b_f32[(p + 6) * n + j];
-                    acc += a_row[p + 7] * b_f32[(p + 7) * n + j];
-                    p += 8;
-                }
-                while p < k {
-                    acc += a_row[p] * b_f32[p * n + j];
-                    p += 1;
-                }
-                row[j] = acc;
+    // Clamp block sizes to the matching global dimension (a block bigger than
+    // the whole matrix is meaningless anyway). This is also what keeps every
+    // `mc*n`/`kb*nb`/`mb*kb` product below safe: `a.len() == m*k*2` and
+    // `b.len() == k*n*2` above already proved `m*k` and `k*n` fit in `usize`,
+    // and `mn` above proved `m*n` does too, so `mc <= m`, `nc <= n`, `kc <= k`
+    // can never make those products overflow the way raw NIF-supplied sizes
+    // could.
+    let mc = if mc == 0 { DEFAULT_MC } else { mc }.min(m.max(1));
+    let nc = if nc == 0 { DEFAULT_NC } else { nc }.min(n.max(1));
+    let kc = if kc == 0 { DEFAULT_KC } else { kc }.min(k.max(1));
+
+    // Only read c_in when it will actually be blended in, preserving the
+    // zero-overhead path for the common alpha=1, beta=0 call.
+    let c_in_f32: Option<Vec<f32>> = if beta != 0.0 {
+        let expected_c = mn.saturating_mul(2);
+        match c_in {
+            Some(bytes) if bytes.len() == expected_c => {
+                Some(T::slice_to_f32(&reinterpret_half_le::<T>(bytes)))
             }
-        });
+            _ => return error(env, "badarg"),
+        }
+    } else {
+        None
+    };
+
+    // Decode to f32 accum types. Both the byte->half reinterpret and the
+    // half->f32 widen are bulk slice operations, not per-element loops.
+    let a_half: Cow<'_, [T]> = reinterpret_half_le(a.as_slice());
+    let b_half: Cow<'_, [T]> = reinterpret_half_le(b.as_slice());
+    let a_f32: Vec<f32> = T::slice_to_f32(&a_half);
+    let b_f32: Vec<f32> = T::slice_to_f32(&b_half);
+
+    // Compute A(m×k) * B(k×n) in f32 via the packed/blocked kernel, pulled
+    // out into `blocked_gemm_f32` so it can be exercised directly in tests
+    // without going through the NIF `Env`/`Binary` plumbing.
+    let mut c_f32 = blocked_gemm_f32(&a_f32, &b_f32, m, n, k, trans_a, trans_b, mc, nc, kc);
+
+    apply_alpha_beta(&mut c_f32, c_in_f32.as_deref(), alpha, beta);
+
+    // Clamp finite values that would otherwise overflow to infinity on the
+    // downcast below; NaN/inf already present in `c_f32` pass through as-is.
+    if saturate {
+        saturate_finite::<T>(&mut c_f32);
+    }
+
+    // Narrow back to the half type in one bulk call, then pack to bytes
+    let mut c_half: Vec<T> = vec![T::from_bits(0); mn];
+    T::slice_from_f32(&mut c_half, &c_f32);
 
-    // Cast to f16 and return as little-endian bytes
-    let c_half: Vec<f16> = c_f32.into_iter().map(|x| f16::from_f32(x)).collect();
+    // Distinguish range loss introduced purely by the half-precision output
+    // format from genuine NaN/inf already present in the f32 accumulator.
+    if strict && check_strict_overflow(&c_f32, &c_half) {
+        return error(env, "overflow");
+    }
 
-    let out_len = mn.checked_mul(2).unwrap_or(usize::MAX);
+    let out_len = mn.saturating_mul(2);
     let mut out = match OwnedBinary::new(out_len) {
         Some(b) => b,
         None => return error(env, "alloc_failed"),
     };
 
-    write_fp16_le_slice(out.as_mut_slice(), &c_half);
+    write_half_le_slice(out.as_mut_slice(), &c_half);
     out.release(env).encode(env)
 }
+
+/// gemm_fp16_acc32(a_bin, b_bin, m, n, k) -> c_bin
+/// A: (m×k) FP16 row-major, B: (k×n) FP16 row-major, C: (m×n) FP16 row-major
+///
+/// The `alpha=1, beta=0` special case of [`gemm_fp16`]; `c_in` is never read.
+#[rustler::nif(schedule = "DirtyCpu")]
+pub fn gemm_fp16_acc32<'a>(env: Env<'a>, a: Binary<'a>, b: Binary<'a>, m: usize, n: usize, k: usize) -> Term<'a> {
+    gemm_acc32::<f16>(env, a, b, None, m, n, k, 1.0, 0.0, false, false, 0, 0, 0, false, false)
+}
+
+/// gemm_bf16_acc32(a_bin, b_bin, m, n, k) -> c_bin
+/// A: (m×k) bf16 row-major, B: (k×n) bf16 row-major, C: (m×n) bf16 row-major
+///
+/// bf16 keeps f32's 8-bit exponent, so large activations that overflow to
+/// `inf` under `gemm_fp16_acc32`'s 5-bit exponent stay finite here.
+#[rustler::nif(schedule = "DirtyCpu")]
+pub fn gemm_bf16_acc32<'a>(env: Env<'a>, a: Binary<'a>, b: Binary<'a>, m: usize, n: usize, k: usize) -> Term<'a> {
+    gemm_acc32::<bf16>(env, a, b, None, m, n, k, 1.0, 0.0, false, false, 0, 0, 0, false, false)
+}
+
+/// gemm_fp16(a_bin, b_bin, c_in_bin, m, n, k, alpha, beta, trans_a, trans_b, mc, nc, kc, saturate, strict) -> c_bin
+/// A: (m×k) FP16 row-major (or (k×m) if `trans_a`), B: (k×n) FP16 row-major
+/// (or (n×k) if `trans_b`), C_in/C: (m×n) FP16 row-major
+///
+/// Full BLAS-style GEMM: `C = alpha*(op_a(A)*op_b(B)) + beta*C_in`. Callers
+/// doing iterative accumulation (attention/MLP blocks summing partial
+/// products) can pass their running total as `c_in` instead of allocating a
+/// fresh output and adding it back in Elixir on every call. `c_in` is
+/// validated against `m*n*2` only when `beta != 0`; otherwise it is never
+/// read, so callers with no accumulator yet can pass an empty binary.
+/// `trans_a`/`trans_b` let a caller hand over `A^T`/`B^T` (e.g. gradients,
+/// weight-tied layers) directly instead of transposing in Elixir first.
+/// `mc`/`nc`/`kc` tune the packed GEMM's cache-block sizes; pass `0` for
+/// any of them to use the built-in defaults. `saturate` clamps finite
+/// results that exceed FP16's ~65504 range to `f16::MAX`/`MIN` instead of
+/// letting them overflow to infinity. `strict` scans the output and
+/// returns `{:error, :overflow}` when a finite f32 accumulator value was
+/// lost to infinity purely by the downcast to FP16 (inputs that were
+/// already NaN/inf are unaffected).
+#[rustler::nif(schedule = "DirtyCpu")]
+#[allow(clippy::too_many_arguments)]
+pub fn gemm_fp16<'a>(
+    env: Env<'a>,
+    a: Binary<'a>,
+    b: Binary<'a>,
+    c_in: Binary<'a>,
+    m: usize,
+    n: usize,
+    k: usize,
+    alpha: f32,
+    beta: f32,
+    trans_a: bool,
+    trans_b: bool,
+    mc: usize,
+    nc: usize,
+    kc: usize,
+    saturate: bool,
+    strict: bool,
+) -> Term<'a> {
+    gemm_acc32::<f16>(
+        env,
+        a,
+        b,
+        Some(c_in.as_slice()),
+        m,
+        n,
+        k,
+        alpha,
+        beta,
+        trans_a,
+        trans_b,
+        mc,
+        nc,
+        kc,
+        saturate,
+        strict,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Naive triple-loop reference, independent of packing/blocking, used to
+    /// check that `blocked_gemm_f32` didn't introduce off-by-one errors at
+    /// block boundaries.
+    fn naive_gemm_f32(a: &[f32], b: &[f32], m: usize, n: usize, k: usize, trans_a: bool, trans_b: bool) -> Vec<f32> {
+        let mut c = vec![0f32; m * n];
+        for i in 0..m {
+            for j in 0..n {
+                let mut acc = 0f32;
+                for p in 0..k {
+                    let a_v = if trans_a { a[p * m + i] } else { a[i * k + p] };
+                    let b_v = if trans_b { b[j * k + p] } else { b[p * n + j] };
+                    acc += a_v * b_v;
+                }
+                c[i * n + j] = acc;
+            }
+        }
+        c
+    }
+
+    /// Deterministic pseudo-random f32s in a small range, avoiding any RNG
+    /// crate dependency just for tests.
+    fn fill(len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                ((state >> 8) as f32 / (1u32 << 24) as f32) * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn assert_close(got: &[f32], want: &[f32]) {
+        assert_eq!(got.len(), want.len());
+        for (g, w) in got.iter().zip(want.iter()) {
+            assert!((g - w).abs() < 1e-3, "got {g}, want {w}");
+        }
+    }
+
+    /// Every shape has at least one dimension that is not a multiple of the
+    /// tiny block size 3 used below, so each call exercises a partial last
+    /// MC, NC or KC block.
+    const SHAPES: &[(usize, usize, usize)] = &[(5, 7, 3), (7, 5, 11), (11, 3, 7), (1, 1, 1), (4, 4, 4)];
+
+    #[test]
+    fn blocked_matches_naive_reference_no_transpose() {
+        for &(m, n, k) in SHAPES {
+            let a = fill(m * k, 1);
+            let b = fill(k * n, 2);
+            let got = blocked_gemm_f32(&a, &b, m, n, k, false, false, 3, 3, 3);
+            let want = naive_gemm_f32(&a, &b, m, n, k, false, false);
+            assert_close(&got, &want);
+        }
+    }
+
+    #[test]
+    fn blocked_matches_naive_reference_trans_a() {
+        for &(m, n, k) in SHAPES {
+            let a = fill(k * m, 3); // A^T is (k×m)
+            let b = fill(k * n, 4);
+            let got = blocked_gemm_f32(&a, &b, m, n, k, true, false, 3, 3, 3);
+            let want = naive_gemm_f32(&a, &b, m, n, k, true, false);
+            assert_close(&got, &want);
+        }
+    }
+
+    #[test]
+    fn blocked_matches_naive_reference_trans_b() {
+        for &(m, n, k) in SHAPES {
+            let a = fill(m * k, 5);
+            let b = fill(n * k, 6); // B^T is (n×k)
+            let got = blocked_gemm_f32(&a, &b, m, n, k, false, true, 3, 3, 3);
+            let want = naive_gemm_f32(&a, &b, m, n, k, false, true);
+            assert_close(&got, &want);
+        }
+    }
+
+    #[test]
+    fn blocked_matches_naive_reference_trans_a_and_b() {
+        for &(m, n, k) in SHAPES {
+            let a = fill(k * m, 7);
+            let b = fill(n * k, 8);
+            let got = blocked_gemm_f32(&a, &b, m, n, k, true, true, 3, 3, 3);
+            let want = naive_gemm_f32(&a, &b, m, n, k, true, true);
+            assert_close(&got, &want);
+        }
+    }
+
+    #[test]
+    fn blocked_matches_naive_reference_block_size_larger_than_shape() {
+        // mc/nc/kc bigger than the matrix itself collapses to a single
+        // block; should still match the naive reference within tolerance.
+        for &(m, n, k) in SHAPES {
+            let a = fill(m * k, 9);
+            let b = fill(k * n, 10);
+            let got = blocked_gemm_f32(&a, &b, m, n, k, false, false, 256, 256, 256);
+            let want = naive_gemm_f32(&a, &b, m, n, k, false, false);
+            assert_close(&got, &want);
+        }
+    }
+
+    #[test]
+    fn apply_alpha_beta_accumulates_with_beta_nonzero() {
+        let mut c = vec![2.0, 3.0, 4.0];
+        let c_in = [10.0, 20.0, 30.0];
+        apply_alpha_beta(&mut c, Some(&c_in), 2.0, 0.5);
+        assert_close(&c, &[2.0 * 2.0 + 0.5 * 10.0, 2.0 * 3.0 + 0.5 * 20.0, 2.0 * 4.0 + 0.5 * 30.0]);
+    }
+
+    #[test]
+    fn apply_alpha_beta_scales_with_no_c_in() {
+        let mut c = vec![1.0, -2.0, 3.5];
+        apply_alpha_beta(&mut c, None, 2.0, 0.0);
+        assert_close(&c, &[2.0, -4.0, 7.0]);
+    }
+
+    #[test]
+    fn apply_alpha_beta_is_noop_for_default_alpha_and_no_c_in() {
+        let mut c = vec![1.0, -2.0, 3.5];
+        apply_alpha_beta(&mut c, None, 1.0, 0.0);
+        assert_close(&c, &[1.0, -2.0, 3.5]);
+    }
+
+    #[test]
+    fn saturate_finite_clamps_values_that_would_overflow_to_inf() {
+        let f16_max = f32::from(f16::MAX);
+        let mut c = vec![f16_max * 2.0, -f16_max * 2.0, 1.0];
+        saturate_finite::<f16>(&mut c);
+        assert_close(&c, &[f16_max, -f16_max, 1.0]);
+    }
+
+    #[test]
+    fn saturate_finite_leaves_nan_and_inf_untouched() {
+        let mut c = vec![f32::NAN, f32::INFINITY, f32::NEG_INFINITY];
+        saturate_finite::<f16>(&mut c);
+        assert!(c[0].is_nan());
+        assert_eq!(c[1], f32::INFINITY);
+        assert_eq!(c[2], f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn check_strict_overflow_detects_finite_value_lost_to_inf() {
+        // Without saturate, a too-large finite f32 narrows to f16::INFINITY:
+        // strict should flag that as overflow.
+        let c_f32 = vec![1.0e9_f32];
+        let mut c_half = vec![f16::from_bits(0)];
+        f16::slice_from_f32(&mut c_half, &c_f32);
+        assert!(check_strict_overflow(&c_f32, &c_half));
+    }
+
+    #[test]
+    fn check_strict_overflow_ignores_genuine_nan_and_inf_inputs() {
+        // A NaN/inf already present in the f32 accumulator narrows to the
+        // matching NaN/inf in f16, which isn't range loss from narrowing.
+        let c_f32 = vec![f32::NAN, f32::INFINITY];
+        let mut c_half = vec![f16::from_bits(0); 2];
+        f16::slice_from_f32(&mut c_half, &c_f32);
+        assert!(!check_strict_overflow(&c_f32, &c_half));
+    }
+
+    #[test]
+    fn check_strict_overflow_is_false_after_saturation() {
+        // Once saturate_finite has clamped a value, narrowing no longer
+        // loses it to infinity, so strict shouldn't fire.
+        let f16_max = f32::from(f16::MAX);
+        let mut c_f32 = vec![f16_max * 2.0];
+        saturate_finite::<f16>(&mut c_f32);
+        let mut c_half = vec![f16::from_bits(0)];
+        f16::slice_from_f32(&mut c_half, &c_f32);
+        assert!(!check_strict_overflow(&c_f32, &c_half));
+    }
+
+    #[test]
+    fn bf16_slice_from_f32_rounds_to_nearest_instead_of_truncating() {
+        // f32 bits: sign=0, exponent=127, mantissa's top 7 bits (the ones
+        // bf16 keeps) are 0000001 and the low 16 bits (the ones bf16 drops)
+        // are 1100000000000000 — more than half a bf16 ULP, so round-to-
+        // nearest must carry into the mantissa. A truncating cast would
+        // just drop those bits and leave the mantissa unchanged.
+        let bits = (127u32 << 23) | (0b0000001u32 << 16) | 0b1100000000000000u32;
+        let v = f32::from_bits(bits);
+
+        let mut out = [bf16::from_bits(0)];
+        bf16::slice_from_f32(&mut out, &[v]);
+
+        let truncated_bits = (bits >> 16) as u16;
+        let rounded_bits = truncated_bits + 1;
+        assert_eq!(out[0].to_bits(), rounded_bits, "bf16 conversion truncated instead of rounding");
+    }
+}